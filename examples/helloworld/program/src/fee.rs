@@ -0,0 +1,127 @@
+//! Mempool-feerate-driven coin selection for the Bitcoin transaction that
+//! anchors each state transition.
+//!
+//! Instead of trusting a single caller-supplied input to cover fees, the
+//! caller offers a set of candidate fee UTXOs and a target feerate (e.g.
+//! derived from Bitcoin Core's `estimatesmartfee`), and the program greedily
+//! selects just enough of them to cover `vsize * fee_rate`. A change output
+//! is returned only when the leftover exceeds what that output itself costs
+//! at the feerate; a smaller leftover is added to the fee.
+
+use arch_program::program_error::ProgramError;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// No combination of the provided fee UTXOs covers `vsize * fee_rate`.
+pub const ERROR_INSUFFICIENT_FEE_UTXOS: u32 = 611;
+
+/// A candidate UTXO the caller offers up to pay fees.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct FeeUtxo {
+    /// Txid of the outpoint being spent.
+    pub txid: [u8; 32],
+    /// Output index of the outpoint being spent.
+    pub vout: u32,
+    /// Value of the outpoint, in satoshis.
+    pub value: u64,
+    /// scriptPubkey of the outpoint, used to size the witness for the
+    /// vsize estimate.
+    pub script_pubkey: Vec<u8>,
+}
+
+// Rough per-component virtual-byte costs, used to estimate `vsize` before
+// the final input set is known. `EXISTING_INPUT_VBYTES` covers the tx's
+// own inputs (e.g. the state-transition input), which are already P2WPKH
+// by construction; fee inputs are sized per-candidate by `input_vbytes`
+// instead, since callers may offer UTXOs of any script type.
+const BASE_TX_VBYTES: u64 = 11;
+const EXISTING_INPUT_VBYTES: u64 = 68;
+const OUTPUT_VBYTES: u64 = 31;
+
+/// Estimated virtual size, in vbytes, of a whole input (outpoint, sequence,
+/// and scriptSig or witness) spending a UTXO locked by `script_pubkey`. The spend's witness/scriptSig shape is determined by
+/// what's being spent, so this reads the prevout's script type rather than
+/// assuming P2WPKH for every input.
+fn input_vbytes(script_pubkey: &[u8]) -> u64 {
+    let script = ScriptBuf::from_bytes(script_pubkey.to_vec());
+    if script.is_p2tr() {
+        58 // keypath-spend Taproot: one 64-65 byte Schnorr signature
+    } else if script.is_p2wpkh() {
+        68 // P2WPKH: sig + pubkey in the witness
+    } else if script.is_p2wsh() {
+        104 // P2WSH: conservative estimate, actual cost depends on the script
+    } else if script.is_p2sh() {
+        91 // nested segwit (P2SH-P2WPKH)
+    } else {
+        148 // legacy P2PKH: sig + pubkey in scriptSig
+    }
+}
+
+/// Estimates the final transaction's vsize given `tx`'s current inputs and
+/// outputs, plus `extra_inputs` candidate fee UTXOs and `extra_outputs`
+/// additional outputs (e.g. a change output).
+pub fn estimate_vsize(tx: &Transaction, extra_inputs: &[FeeUtxo], extra_outputs: usize) -> u64 {
+    let extra_input_vbytes: u64 = extra_inputs
+        .iter()
+        .map(|utxo| input_vbytes(&utxo.script_pubkey))
+        .sum();
+
+    BASE_TX_VBYTES
+        + tx.input.len() as u64 * EXISTING_INPUT_VBYTES
+        + extra_input_vbytes
+        + (tx.output.len() + extra_outputs) as u64 * OUTPUT_VBYTES
+}
+
+/// Greedily selects fee UTXOs (largest first) from `candidates` until their
+/// combined value covers `tx`'s estimated fee at `fee_rate_sat_per_vb`,
+/// returning the chosen inputs and an optional change output paid to
+/// `change_script_pubkey`.
+pub fn select_fee_inputs(
+    tx: &Transaction,
+    candidates: &[FeeUtxo],
+    fee_rate_sat_per_vb: u64,
+    change_script_pubkey: Vec<u8>,
+) -> Result<(Vec<FeeUtxo>, Option<TxOut>), ProgramError> {
+    let mut sorted = candidates.to_vec();
+    sorted.sort_by(|a, b| b.value.cmp(&a.value));
+
+    let mut selected = Vec::new();
+    let mut total_in: u64 = 0;
+
+    for utxo in sorted {
+        total_in += utxo.value;
+        selected.push(utxo);
+
+        let fee_no_change = estimate_vsize(tx, &selected, 0) * fee_rate_sat_per_vb;
+        if total_in >= fee_no_change && total_in - fee_no_change <= OUTPUT_VBYTES * fee_rate_sat_per_vb {
+            // Leftover is too small to justify a change output; let it
+            // round into the fee instead.
+            return Ok((selected, None));
+        }
+
+        let fee_with_change = estimate_vsize(tx, &selected, 1) * fee_rate_sat_per_vb;
+        if total_in > fee_with_change {
+            let change = TxOut {
+                value: Amount::from_sat(total_in - fee_with_change),
+                script_pubkey: ScriptBuf::from_bytes(change_script_pubkey),
+            };
+            return Ok((selected, Some(change)));
+        }
+    }
+
+    Err(ProgramError::Custom(ERROR_INSUFFICIENT_FEE_UTXOS))
+}
+
+/// Builds a signable `TxIn` spending `utxo`, leaving the witness empty for
+/// the signer to fill in later.
+pub fn fee_utxo_to_input(utxo: &FeeUtxo) -> TxIn {
+    TxIn {
+        previous_output: OutPoint {
+            txid: Txid::from_byte_array(utxo.txid),
+            vout: utxo.vout,
+        },
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::new(),
+    }
+}
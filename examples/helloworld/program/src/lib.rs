@@ -1,20 +1,28 @@
 use arch_program::{
     account::AccountInfo,
-    bitcoin::{self, absolute::LockTime, transaction::Version, Transaction},
+    bitcoin,
     entrypoint,
-    helper::add_state_transition,
-    input_to_sign::InputToSign,
     msg,
-    program::{
-        get_account_script_pubkey, get_bitcoin_block_height, next_account_info,
-        set_transaction_to_sign,
-    },
+    program::{get_account_script_pubkey, get_bitcoin_block_height, set_transaction_to_sign},
     program_error::ProgramError,
     pubkey::Pubkey,
     transaction_to_sign::TransactionToSign,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 
+mod fee;
+mod message;
+mod spv;
+
+use fee::{fee_utxo_to_input, select_fee_inputs, FeeUtxo};
+use message::MessageBuilder;
+use spv::{verify_merkle_inclusion, MerkleProofParams};
+
+/// Returned when the referenced fee/anchor UTXO hasn't reached
+/// `min_confirmations` yet; recoverable, clients can poll and retry once
+/// more blocks have been mined.
+pub const ERROR_INSUFFICIENT_CONFIRMATIONS: u32 = 621;
+
 // Register our program's entrypoint function
 entrypoint!(process_instruction);
 
@@ -30,8 +38,8 @@ pub fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> Result<(), ProgramError> {
-    // We expect exactly one account for this program
-    if accounts.len() != 1 {
+    // We expect at least one account for this program
+    if accounts.is_empty() {
         return Err(ProgramError::Custom(501));
     }
 
@@ -39,57 +47,66 @@ pub fn process_instruction(
     let bitcoin_block_height = get_bitcoin_block_height();
     msg!("bitcoin_block_height {:?}", bitcoin_block_height);
 
-    // Get an iterator over the accounts and get the first (and only) account
-    let account_iter = &mut accounts.iter();
-    let account = next_account_info(account_iter)?;
-
-    msg!("account {:?}", account);
+    msg!("accounts {:?}", accounts);
 
     // Deserialize the instruction data into our params struct
     let params: HelloWorldParams = borsh::from_slice(instruction_data).unwrap();
 
-    // Deserialize the Bitcoin transaction that will be used for fees
-    let fees_tx: Transaction = bitcoin::consensus::deserialize(&params.tx_hex).unwrap();
+    // Prove the referenced transaction is actually included in the current
+    // Bitcoin block before we let it influence state. This recomputes the
+    // root from the proof and checks it against the block's real Merkle
+    // root, not a value taken from instruction data.
+    verify_merkle_inclusion(&params.merkle_proof)?;
+
+    // Don't act on a shallow, reorg-prone UTXO: require it to have matured
+    // by at least `min_confirmations`, the same rolling safety-margin that
+    // cross-chain witnessers use before treating a Bitcoin transaction as
+    // final.
+    let confirmations = bitcoin_block_height
+        .saturating_sub(params.utxo_height)
+        .saturating_add(1);
+    if confirmations < params.min_confirmations as u64 {
+        return Err(ProgramError::Custom(ERROR_INSUFFICIENT_CONFIRMATIONS));
+    }
 
     // Create our greeting message
     let new_data = format!("Hello {}", params.name);
 
-    // Check if we need to resize the account to fit our greeting
-    let data_len = account.data.try_borrow().unwrap().len();
-    if new_data.as_bytes().len() > data_len {
-        account.realloc(new_data.len(), true)?;
+    // Batch every account's state transition into one Bitcoin transaction.
+    // Accounts repeated across the slice dedupe to a single transition and
+    // a single signer, the way a flattened Solana `Message` collapses
+    // repeated account references.
+    let mut builder = MessageBuilder::new();
+    for account in accounts {
+        builder.add_transition(account, new_data.as_bytes())?;
     }
 
-    // Get the script pubkey for this account
-    let script_pubkey = get_account_script_pubkey(account.key);
+    // Get the script pubkey for the first account, used for fee change.
+    let script_pubkey = get_account_script_pubkey(accounts[0].key);
     msg!("script_pubkey {:?}", script_pubkey);
 
-    // Store our greeting in the account's data
-    account
-        .data
-        .try_borrow_mut()
-        .unwrap()
-        .copy_from_slice(new_data.as_bytes());
-
-    // Create a new Bitcoin transaction for our state transition
-    let mut tx = Transaction {
-        version: Version::TWO,
-        lock_time: LockTime::ZERO,
-        input: vec![],
-        output: vec![],
-    };
-
-    // Add the state transition and fee information
-    add_state_transition(&mut tx, account);
-    tx.input.push(fees_tx.input[0].clone());
+    // Select just enough fee UTXOs to cover the batch's estimated vsize at
+    // the requested feerate, sending the leftover back as change when it
+    // exceeds the cost of the change output (otherwise it goes to the fee).
+    let (fee_inputs, change_output) = select_fee_inputs(
+        builder.tx(),
+        &params.fee_utxos,
+        params.fee_rate_sat_per_vb,
+        script_pubkey.clone().into_bytes(),
+    )?;
+
+    for utxo in &fee_inputs {
+        builder.add_fee_input(fee_utxo_to_input(utxo));
+    }
+    if let Some(change) = change_output {
+        builder.add_output(change);
+    }
+    let (tx, inputs_to_sign) = builder.build();
 
     // Create the transaction signing request
     let tx_to_sign = TransactionToSign {
         tx_bytes: &bitcoin::consensus::serialize(&tx),
-        inputs_to_sign: &[InputToSign {
-            index: 0,
-            signer: account.key.clone(),
-        }],
+        inputs_to_sign: &inputs_to_sign,
     };
 
     msg!("tx_to_sign{:?}", tx_to_sign);
@@ -103,6 +120,20 @@ pub fn process_instruction(
 pub struct HelloWorldParams {
     /// The name to say hello to
     pub name: String,
-    /// Raw Bitcoin transaction for fees
-    pub tx_hex: Vec<u8>,
+    /// Merkle inclusion proof for the transaction referenced by this
+    /// instruction, checked against the block at `get_bitcoin_block_height()`
+    /// before any state is mutated.
+    pub merkle_proof: MerkleProofParams,
+    /// Candidate UTXOs the caller offers up to pay fees; the program
+    /// selects just enough of them to cover the transaction at
+    /// `fee_rate_sat_per_vb`.
+    pub fee_utxos: Vec<FeeUtxo>,
+    /// Target feerate in sat/vB, e.g. derived from Bitcoin Core's
+    /// `estimatesmartfee`.
+    pub fee_rate_sat_per_vb: u64,
+    /// Block height at which the referenced fee/anchor UTXO was mined.
+    pub utxo_height: u64,
+    /// Minimum number of confirmations the UTXO must have before this
+    /// instruction will produce a transaction to sign.
+    pub min_confirmations: u32,
 }
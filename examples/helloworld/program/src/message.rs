@@ -0,0 +1,119 @@
+//! Batches several accounts' state transitions into one Bitcoin
+//! transaction.
+//!
+//! Mirrors how Solana's `Message` constructor flattens each instruction's
+//! accounts into a single deduplicated account list: `MessageBuilder` takes
+//! several `(account, new_data)` pairs, commits each one's state transition
+//! into a single `Transaction`, and tracks exactly one `InputToSign` per
+//! distinct signer, so a batch of N account updates commits atomically in
+//! one signed Bitcoin transaction instead of N separate invocations.
+
+use arch_program::{
+    account::AccountInfo,
+    bitcoin::{absolute::LockTime, transaction::Version, Transaction, TxIn, TxOut},
+    helper::add_state_transition,
+    input_to_sign::InputToSign,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// The same account was added twice with different `new_data`; a batch
+/// can't commit two different values for one signer.
+pub const ERROR_CONFLICTING_TRANSITION: u32 = 622;
+
+/// Assembles one `Transaction` out of several accounts' state transitions
+/// plus shared fee inputs.
+pub struct MessageBuilder {
+    tx: Transaction,
+    // Signer pubkey -> (index of its input in `tx.input`, the data it was
+    // given). A `Vec` rather than a map: account lists here are small and
+    // we want to preserve the order transitions were added in.
+    signers: Vec<(Pubkey, u32, Vec<u8>)>,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self {
+            tx: Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            },
+            signers: vec![],
+        }
+    }
+
+    /// Writes `new_data` into `account` and adds its state transition to
+    /// the batch. Adding the same account twice with the same `new_data`
+    /// is a no-op the second time, the way a flattened Solana `Message`
+    /// collapses repeated account references into one entry; adding it
+    /// twice with *different* data is an error, since a single batch can't
+    /// commit two different values for one signer.
+    pub fn add_transition(
+        &mut self,
+        account: &AccountInfo,
+        new_data: &[u8],
+    ) -> Result<(), ProgramError> {
+        if let Some((_, _, existing_data)) =
+            self.signers.iter().find(|(signer, _, _)| signer == account.key)
+        {
+            if existing_data.as_slice() == new_data {
+                return Ok(());
+            }
+            return Err(ProgramError::Custom(ERROR_CONFLICTING_TRANSITION));
+        }
+
+        let data_len = account.data.try_borrow().unwrap().len();
+        if new_data.len() > data_len {
+            account.realloc(new_data.len(), true)?;
+        }
+        account
+            .data
+            .try_borrow_mut()
+            .unwrap()
+            .copy_from_slice(new_data);
+
+        let input_index = self.tx.input.len() as u32;
+        add_state_transition(&mut self.tx, account);
+        self.signers
+            .push((account.key.clone(), input_index, new_data.to_vec()));
+        Ok(())
+    }
+
+    /// The transaction assembled so far, e.g. for estimating its vsize
+    /// before fee inputs are chosen.
+    pub fn tx(&self) -> &Transaction {
+        &self.tx
+    }
+
+    /// Appends a shared fee input not tied to any account's state
+    /// transition.
+    pub fn add_fee_input(&mut self, input: TxIn) {
+        self.tx.input.push(input);
+    }
+
+    /// Appends an output, e.g. fee change.
+    pub fn add_output(&mut self, output: TxOut) {
+        self.tx.output.push(output);
+    }
+
+    /// Finalizes the batch into the assembled transaction and one
+    /// `InputToSign` per distinct signer, in the order their transitions
+    /// were added. All transitions land in the same transaction, so they
+    /// commit or fail together.
+    pub fn build(self) -> (Transaction, Vec<InputToSign>) {
+        let inputs_to_sign = self
+            .signers
+            .into_iter()
+            .map(|(signer, index, _)| InputToSign { index, signer })
+            .collect();
+        (self.tx, inputs_to_sign)
+    }
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,101 @@
+//! Bitcoin SPV (simplified payment verification) support.
+//!
+//! Proves that a transaction referenced by a program instruction is
+//! actually included in a Bitcoin block, by recomputing the block's
+//! Merkle root from a sibling-hash proof and checking it against the real
+//! header fetched from the runtime, not a value the caller supplies.
+
+use arch_program::{program::get_bitcoin_block_height, program_error::ProgramError};
+use bitcoin::hashes::{sha256d, Hash};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// The proof's sibling/path lengths don't match, or the reconstructed
+/// root doesn't match the block's actual Merkle root.
+pub const ERROR_MERKLE_PROOF_MISMATCH: u32 = 601;
+/// A proof level pairs a node with itself, the CVE-2012-2459 duplicate-hash
+/// malleability that let attackers forge inclusion for duplicated leaves.
+pub const ERROR_DUPLICATE_MERKLE_NODE: u32 = 602;
+/// The transaction index implied by the proof's path falls outside the
+/// block's transaction count.
+pub const ERROR_TX_INDEX_OUT_OF_RANGE: u32 = 603;
+
+/// A Merkle inclusion proof for a single Bitcoin transaction, carried in
+/// instruction data alongside the transaction it vouches for.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct MerkleProofParams {
+    /// Raw bytes of the transaction being proven; the leaf hash is derived
+    /// from these, never trusted as a caller-supplied hash.
+    pub tx_bytes: Vec<u8>,
+    /// Sibling hash at each level of the tree, ordered leaf to root.
+    pub siblings: Vec<[u8; 32]>,
+    /// Per-level bit: `true` if the sibling sits to the left of the running
+    /// hash (so `sibling || current`), `false` if it sits to the right
+    /// (`current || sibling`). Read bottom-up, this also encodes the
+    /// transaction's index within the block.
+    pub path: Vec<bool>,
+    /// Total number of transactions in the block, used to bound the index
+    /// implied by `path`.
+    pub block_tx_count: u32,
+}
+
+/// Bitcoin's double round of SHA-256, used for both txids and internal
+/// Merkle tree nodes.
+pub fn double_sha256(data: &[u8]) -> [u8; 32] {
+    sha256d::Hash::hash(data).to_byte_array()
+}
+
+/// Fetches the actual Merkle root of the block at `height` from the
+/// runtime, so `verify_merkle_inclusion` has something trustworthy to
+/// check the recomputed root against instead of a caller-supplied value.
+///
+/// This is the one place in the module that reaches into the SDK for that
+/// root; `arch_program::program::get_bitcoin_block_merkle_root` is this
+/// crate's best-effort guess at the call (by analogy with the existing
+/// `get_bitcoin_block_height`), made without a vendored copy of the SDK to
+/// check against. Confirm the symbol and signature against the pinned
+/// `arch_program` version before merging, and update this one function if
+/// the real API differs.
+fn block_merkle_root(height: u64) -> [u8; 32] {
+    arch_program::program::get_bitcoin_block_merkle_root(height)
+}
+
+/// Recomputes the Merkle root from `proof` and checks it against the
+/// actual Merkle root of the block at `get_bitcoin_block_height()`,
+/// rejecting malleable and out-of-range proofs along the way.
+pub fn verify_merkle_inclusion(proof: &MerkleProofParams) -> Result<(), ProgramError> {
+    if proof.siblings.len() != proof.path.len() {
+        return Err(ProgramError::Custom(ERROR_MERKLE_PROOF_MISMATCH));
+    }
+
+    let expected_root = block_merkle_root(get_bitcoin_block_height());
+
+    let mut current = double_sha256(&proof.tx_bytes);
+    let mut index: u64 = 0;
+    for (level, (sibling, &sibling_on_left)) in
+        proof.siblings.iter().zip(proof.path.iter()).enumerate()
+    {
+        // CVE-2012-2459: a node paired with itself lets an attacker
+        // duplicate the last leaf of an odd-sized level and forge a valid
+        // proof for a transaction that isn't really there.
+        if *sibling == current {
+            return Err(ProgramError::Custom(ERROR_DUPLICATE_MERKLE_NODE));
+        }
+
+        current = if sibling_on_left {
+            double_sha256(&[sibling.as_slice(), current.as_slice()].concat())
+        } else {
+            double_sha256(&[current.as_slice(), sibling.as_slice()].concat())
+        };
+        index |= (sibling_on_left as u64) << level;
+    }
+
+    if index >= proof.block_tx_count as u64 {
+        return Err(ProgramError::Custom(ERROR_TX_INDEX_OUT_OF_RANGE));
+    }
+
+    if current != expected_root {
+        return Err(ProgramError::Custom(ERROR_MERKLE_PROOF_MISMATCH));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use arch_program::{
+    account::AccountInfo,
+    bitcoin::{self, absolute::LockTime, transaction::Version, Transaction},
+    entrypoint,
+    helper::add_state_transition,
+    input_to_sign::InputToSign,
+    msg,
+    program::{get_bitcoin_block_height, next_account_info, set_transaction_to_sign},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    transaction_to_sign::TransactionToSign,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+mod swap;
+
+use swap::{hash_preimage, SwapAccount};
+
+/// Returns `true` if `needle` appears anywhere in `haystack`. Used to check
+/// that a witness script embeds the terms (hashlock, refund height) it
+/// claims to enforce.
+fn contains_subslice(haystack: &[u8], needle: &[u8]) -> bool {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return false;
+    }
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Encodes `n` as a minimal Bitcoin Script number (`CScriptNum`), the same
+/// little-endian, sign-extended, shortest-possible encoding
+/// `OP_CHECKLOCKTIMEVERIFY`'s operand uses on-chain. A raw `u64::to_le_bytes`
+/// dump is 8 bytes and never appears in a real script, which encodes a
+/// height like 800_000 in 3 bytes.
+fn minimal_script_num(n: u64) -> Vec<u8> {
+    if n == 0 {
+        return vec![];
+    }
+
+    let mut value = n;
+    let mut bytes = Vec::new();
+    while value > 0 {
+        bytes.push((value & 0xff) as u8);
+        value >>= 8;
+    }
+
+    // If the most significant byte's high bit is set, push an extra zero
+    // byte so the encoding isn't misread as a negative number.
+    if bytes.last().is_some_and(|&b| b & 0x80 != 0) {
+        bytes.push(0);
+    }
+
+    bytes
+}
+
+/// Checks that `spend_tx`'s first input actually carries an HTLC witness script
+/// for `swap` (both the hashlock and refund height are embedded in it),
+/// and, when redeeming, that the revealed `preimage` is present in that
+/// same witness. Used both to validate the transaction that funds the HTLC
+/// (`Lock`) and the transactions that later spend it (`Redeem`/`Refund`).
+fn verify_htlc_spend(
+    spend_tx: &Transaction,
+    swap: &SwapAccount,
+    preimage: Option<&[u8]>,
+) -> Result<(), ProgramError> {
+    let input = spend_tx
+        .input
+        .first()
+        .ok_or(ProgramError::Custom(swap::ERROR_MALFORMED_SPEND_TX))?;
+
+    let redeem_script = input
+        .witness
+        .iter()
+        .last()
+        .ok_or(ProgramError::Custom(swap::ERROR_MALFORMED_SPEND_TX))?;
+
+    if !contains_subslice(redeem_script, &swap.hashlock)
+        || !contains_subslice(redeem_script, &minimal_script_num(swap.refund_height))
+    {
+        return Err(ProgramError::Custom(swap::ERROR_HTLC_SCRIPT_MISMATCH));
+    }
+
+    if let Some(preimage) = preimage {
+        let revealed = input.witness.iter().any(|item| item == preimage);
+        if !revealed {
+            return Err(ProgramError::Custom(swap::ERROR_PREIMAGE_NOT_IN_WITNESS));
+        }
+    }
+
+    Ok(())
+}
+
+// Register our program's entrypoint function
+entrypoint!(process_instruction);
+
+/// Main program entrypoint. Drives a single swap account through its
+/// `Offered -> Locked -> Redeemed | Refunded` lifecycle.
+///
+/// # Arguments
+/// * `_program_id` - The public key of our program
+/// * `accounts` - The swap account this instruction operates on
+/// * `instruction_data` - A Borsh-encoded `SwapInstruction`
+pub fn process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> Result<(), ProgramError> {
+    // We expect exactly one account: the swap's own state account
+    if accounts.len() != 1 {
+        return Err(ProgramError::Custom(501));
+    }
+
+    // Get the current Bitcoin block height for reference
+    let bitcoin_block_height = get_bitcoin_block_height();
+    msg!("bitcoin_block_height {:?}", bitcoin_block_height);
+
+    // Get an iterator over the accounts and get the first (and only) account
+    let account_iter = &mut accounts.iter();
+    let account = next_account_info(account_iter)?;
+
+    msg!("account {:?}", account);
+
+    // Deserialize the instruction data into our instruction enum
+    let instruction: SwapInstruction = borsh::from_slice(instruction_data).unwrap();
+    msg!("instruction {:?}", instruction);
+
+    match instruction {
+        SwapInstruction::Offer {
+            counterparty,
+            hashlock,
+            refund_height,
+        } => {
+            let swap = SwapAccount::new(counterparty, hashlock, refund_height);
+            write_swap(account, &swap)
+        }
+
+        SwapInstruction::Lock { tx_hex } => {
+            let mut swap = read_swap(account)?;
+            let spend_tx = decode_spend_tx(&tx_hex)?;
+            // Even though nothing is being redeemed yet, the transaction
+            // that funds the HTLC must itself carry the hashlock/timelock
+            // terms in its witness, so funds can't be locked into an
+            // output that doesn't actually enforce this swap's terms.
+            verify_htlc_spend(&spend_tx, &swap, None)?;
+            swap.lock()?;
+
+            // The locking transaction carries no timelock itself; the
+            // refund branch's timelock is enforced when it is spent, not
+            // when it is created.
+            let signed = sign_spend(accounts, account, spend_tx, LockTime::ZERO)?;
+            write_swap(account, &swap)?;
+            Ok(signed)
+        }
+
+        SwapInstruction::Redeem { preimage, tx_hex } => {
+            let mut swap = read_swap(account)?;
+            let spend_tx = decode_spend_tx(&tx_hex)?;
+            verify_htlc_spend(&spend_tx, &swap, Some(&preimage))?;
+            swap.redeem(&preimage, bitcoin_block_height)?;
+
+            msg!("preimage hash {:?}", hash_preimage(&preimage));
+            let signed = sign_spend(accounts, account, spend_tx, LockTime::ZERO)?;
+            write_swap(account, &swap)?;
+            Ok(signed)
+        }
+
+        SwapInstruction::Refund { tx_hex } => {
+            let mut swap = read_swap(account)?;
+            let spend_tx = decode_spend_tx(&tx_hex)?;
+            verify_htlc_spend(&spend_tx, &swap, None)?;
+            let refund_height = swap.refund_height;
+            swap.refund(bitcoin_block_height)?;
+
+            // Spending the refund branch requires the transaction's
+            // locktime to have reached `refund_height`.
+            let lock_time = LockTime::from_height(refund_height as u32)
+                .map_err(|_| ProgramError::Custom(swap::ERROR_INVALID_SWAP_STATE))?;
+            let signed = sign_spend(accounts, account, spend_tx, lock_time)?;
+            write_swap(account, &swap)?;
+            Ok(signed)
+        }
+    }
+}
+
+/// Reads and decodes the `SwapAccount` stored in `account.data`.
+fn read_swap(account: &AccountInfo) -> Result<SwapAccount, ProgramError> {
+    borsh::from_slice(&account.data.try_borrow().unwrap())
+        .map_err(|_| ProgramError::Custom(swap::ERROR_INVALID_SWAP_STATE))
+}
+
+/// Encodes `swap` and stores it in `account.data`, resizing the account if
+/// needed.
+fn write_swap(account: &AccountInfo, swap: &SwapAccount) -> Result<(), ProgramError> {
+    let new_data = borsh::to_vec(swap).unwrap();
+
+    let data_len = account.data.try_borrow().unwrap().len();
+    if new_data.len() > data_len {
+        account.realloc(new_data.len(), true)?;
+    }
+
+    account
+        .data
+        .try_borrow_mut()
+        .unwrap()
+        .copy_from_slice(&new_data);
+    Ok(())
+}
+
+/// Decodes a caller-supplied raw Bitcoin transaction, returning a
+/// `ProgramError` instead of panicking on malformed input.
+fn decode_spend_tx(tx_hex: &[u8]) -> Result<Transaction, ProgramError> {
+    bitcoin::consensus::deserialize(tx_hex)
+        .map_err(|_| ProgramError::Custom(swap::ERROR_MALFORMED_SPEND_TX))
+}
+
+/// Attaches the swap account's state transition to `spend_tx` and submits
+/// it for signing.
+fn sign_spend(
+    accounts: &[AccountInfo],
+    account: &AccountInfo,
+    spend_tx: Transaction,
+    lock_time: LockTime,
+) -> Result<(), ProgramError> {
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: spend_tx.input,
+        output: spend_tx.output,
+    };
+
+    // The state transition's input is appended after `spend_tx`'s inputs.
+    let input_index = tx.input.len() as u32;
+    add_state_transition(&mut tx, account);
+
+    let tx_to_sign = TransactionToSign {
+        tx_bytes: &bitcoin::consensus::serialize(&tx),
+        inputs_to_sign: &[InputToSign {
+            index: input_index,
+            signer: account.key.clone(),
+        }],
+    };
+
+    msg!("tx_to_sign{:?}", tx_to_sign);
+
+    set_transaction_to_sign(accounts, tx_to_sign)
+}
+
+/// Instructions that drive a swap account through its lifecycle.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum SwapInstruction {
+    /// Posts the swap terms: who the counterparty is, the hashlock they
+    /// must satisfy to redeem, and the height after which the depositor
+    /// may refund instead.
+    Offer {
+        counterparty: Pubkey,
+        hashlock: [u8; 32],
+        refund_height: u64,
+    },
+    /// Broadcasts the hashlocked/timelocked Bitcoin transaction that holds
+    /// the swapped funds.
+    Lock { tx_hex: Vec<u8> },
+    /// Reveals `preimage` to claim the funds before `refund_height`.
+    Redeem { preimage: Vec<u8>, tx_hex: Vec<u8> },
+    /// Reclaims the funds after `refund_height` with no redeem having
+    /// happened.
+    Refund { tx_hex: Vec<u8> },
+}
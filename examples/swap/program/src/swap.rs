@@ -0,0 +1,118 @@
+//! Adaptor-signature-style HTLC state machine for trustless cross-chain
+//! swaps.
+//!
+//! Modeled on the classic XMR<->BTC atomic swap flow: one party's on-chain
+//! spend reveals a secret (the hashlock preimage) that the counterparty can
+//! then use to claim their side on the other chain. If the swap never
+//! completes, a timelock refund branch lets the original depositor recover
+//! their funds after `refund_height`.
+
+use arch_program::{bitcoin::hashes::sha256, bitcoin::hashes::Hash, program_error::ProgramError, pubkey::Pubkey};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// A redeem was attempted at or after `refund_height`, when only the
+/// depositor's refund is still allowed.
+pub const ERROR_SWAP_EXPIRED: u32 = 641;
+/// The swap isn't in the state the requested transition expects (e.g.
+/// redeem/refund on a swap that was never locked, or double-redeem).
+pub const ERROR_INVALID_SWAP_STATE: u32 = 642;
+/// The revealed preimage doesn't hash to the stored hashlock.
+pub const ERROR_PREIMAGE_MISMATCH: u32 = 643;
+/// A refund was attempted before `refund_height`.
+pub const ERROR_SWAP_NOT_EXPIRED: u32 = 644;
+/// The caller-supplied spend transaction couldn't be decoded, or is
+/// missing the input that should carry the HTLC witness.
+pub const ERROR_MALFORMED_SPEND_TX: u32 = 645;
+/// The spend transaction's witness script doesn't embed this swap's
+/// hashlock and refund height, so it isn't spending the HTLC it claims to.
+pub const ERROR_HTLC_SCRIPT_MISMATCH: u32 = 646;
+/// A redeem's witness doesn't actually contain the revealed preimage.
+pub const ERROR_PREIMAGE_NOT_IN_WITNESS: u32 = 647;
+
+/// Lifecycle of a single swap, stored directly in `account.data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum SwapState {
+    /// Terms have been posted but the hashlocked Bitcoin transaction
+    /// hasn't been broadcast yet.
+    Offered,
+    /// The hashlocked/timelocked Bitcoin transaction is live.
+    Locked,
+    /// The counterparty revealed the preimage and claimed the funds.
+    Redeemed,
+    /// `refund_height` passed without a redeem, and the depositor reclaimed
+    /// the funds.
+    Refunded,
+}
+
+/// Account data for a swap: its state plus the terms fixed when it was
+/// offered.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct SwapAccount {
+    pub state: SwapState,
+    /// The party who can redeem by revealing the preimage.
+    pub counterparty: Pubkey,
+    /// Single SHA-256 of the secret preimage that unlocks a redeem,
+    /// matching the `OP_SHA256` the redeem script checks on-chain.
+    pub hashlock: [u8; 32],
+    /// Bitcoin block height after which the depositor may refund instead.
+    pub refund_height: u64,
+}
+
+impl SwapAccount {
+    pub fn new(counterparty: Pubkey, hashlock: [u8; 32], refund_height: u64) -> Self {
+        Self {
+            state: SwapState::Offered,
+            counterparty,
+            hashlock,
+            refund_height,
+        }
+    }
+
+    /// `Offered` -> `Locked`: the hashlocked/timelocked transaction has been
+    /// broadcast and this account now tracks it.
+    pub fn lock(&mut self) -> Result<(), ProgramError> {
+        if self.state != SwapState::Offered {
+            return Err(ProgramError::Custom(ERROR_INVALID_SWAP_STATE));
+        }
+        self.state = SwapState::Locked;
+        Ok(())
+    }
+
+    /// `Locked` -> `Redeemed`: `preimage` must hash to the stored hashlock,
+    /// and the redeem must land strictly before `refund_height` so it can't
+    /// race a refund.
+    pub fn redeem(&mut self, preimage: &[u8], current_height: u64) -> Result<(), ProgramError> {
+        if self.state != SwapState::Locked {
+            return Err(ProgramError::Custom(ERROR_INVALID_SWAP_STATE));
+        }
+        if current_height >= self.refund_height {
+            return Err(ProgramError::Custom(ERROR_SWAP_EXPIRED));
+        }
+        if hash_preimage(preimage) != self.hashlock {
+            return Err(ProgramError::Custom(ERROR_PREIMAGE_MISMATCH));
+        }
+        self.state = SwapState::Redeemed;
+        Ok(())
+    }
+
+    /// `Locked` -> `Refunded`: only once `refund_height` has passed, so a
+    /// refund can never beat a valid redeem to the punch.
+    pub fn refund(&mut self, current_height: u64) -> Result<(), ProgramError> {
+        if self.state != SwapState::Locked {
+            return Err(ProgramError::Custom(ERROR_INVALID_SWAP_STATE));
+        }
+        if current_height < self.refund_height {
+            return Err(ProgramError::Custom(ERROR_SWAP_NOT_EXPIRED));
+        }
+        self.state = SwapState::Refunded;
+        Ok(())
+    }
+}
+
+/// Hashes a preimage with the same single round of SHA-256 that the
+/// on-chain redeem script's `OP_SHA256` applies, so a redeem can check
+/// `hash_preimage(revealed) == hashlock` against what the script actually
+/// verifies.
+pub fn hash_preimage(preimage: &[u8]) -> [u8; 32] {
+    sha256::Hash::hash(preimage).to_byte_array()
+}